@@ -39,58 +39,291 @@ impl<'a> Command<'a> {
     fn path(&self) -> &str {
         self.args[0]
     }
+
+    /// Returns this command's arguments, including the path at index `0`.
+    fn args(&self) -> &[&str] {
+        &self.args
+    }
+}
+
+/// Error returned by a registered command's handler.
+#[derive(Debug)]
+pub enum CmdError {
+    /// The command was invoked with the wrong number or kind of arguments.
+    Usage,
+    /// The command failed for a command-specific reason.
+    Failed
+}
+
+/// A registered command's handler. `args[0]` is the command's own name.
+pub type CmdFn = fn(args: &[&str]) -> Result<(), CmdError>;
+
+/// The maximum number of commands a `Shell` can hold.
+const MAX_COMMANDS: usize = 16;
+
+/// A single entry in a `Shell`'s command registry.
+#[derive(Copy, Clone)]
+struct CmdEntry {
+    name: &'static str,
+    help: &'static str,
+    run: CmdFn
+}
+
+/// A registry of shell commands, dispatched by name.
+///
+/// Commands are registered with `register` before the shell starts reading
+/// input; the built-in `help` command lists whatever has been registered.
+/// Backed by a fixed-size array so registration never allocates.
+struct Shell {
+    commands: [Option<CmdEntry>; MAX_COMMANDS],
+    len: usize
+}
+
+impl Shell {
+    /// Returns a new, empty command registry.
+    fn new() -> Shell {
+        Shell { commands: [None; MAX_COMMANDS], len: 0 }
+    }
+
+    /// Registers `name` as a command that runs `run` when invoked. `help` is
+    /// shown next to `name` by the built-in `help` command.
+    ///
+    /// Silently drops the registration if the registry is full.
+    fn register(&mut self, name: &'static str, help: &'static str, run: CmdFn) {
+        if self.len < self.commands.len() {
+            self.commands[self.len] = Some(CmdEntry { name, help, run });
+            self.len += 1;
+        }
+    }
+
+    /// Returns the registered entry named `name`, if any.
+    fn find(&self, name: &str) -> Option<&CmdEntry> {
+        self.commands[..self.len].iter()
+            .filter_map(|entry| entry.as_ref())
+            .find(|entry| entry.name == name)
+    }
+
+    /// Prints the name and help text of every registered command.
+    fn help(&self) {
+        kprintln!("Available commands:");
+        for entry in self.commands[..self.len].iter().filter_map(|entry| entry.as_ref()) {
+            kprintln!("  {} - {}", entry.name, entry.help);
+        }
+    }
+
+    /// Looks `command` up by name and runs it, printing an error if it's
+    /// unknown or if it returns one.
+    fn dispatch(&self, command: &Command) {
+        if command.path() == "help" {
+            return self.help();
+        }
+
+        match self.find(command.path()) {
+            Some(entry) => if let Err(e) = (entry.run)(command.args()) {
+                kprintln!("Error: {:?}", e);
+            },
+            None => kprintln!("Error: unknown command: {}", command.path())
+        }
+    }
+}
+
+/// The built-in `echo` command: prints its arguments back out.
+fn echo(args: &[&str]) -> Result<(), CmdError> {
+    for arg in &args[1..] {
+        kprint!("{} ", arg);
+    }
+    kprintln!("");
+    Ok(())
+}
+
+/// Maximum number of bytes a single remembered line can hold.
+const LINE_CAP: usize = 512;
+/// Maximum number of previously entered lines the shell remembers.
+const HISTORY_LEN: usize = 16;
+
+/// A fixed-size ring buffer of previously entered lines, walked by the
+/// up/down arrow keys. Entries are fixed-capacity byte buffers so recalling
+/// history never allocates.
+struct History {
+    lines: [[u8; LINE_CAP]; HISTORY_LEN],
+    lens: [usize; HISTORY_LEN],
+    count: usize,
+    head: usize
+}
+
+impl History {
+    /// Returns a new, empty history.
+    fn new() -> History {
+        History {
+            lines: [[0; LINE_CAP]; HISTORY_LEN],
+            lens: [0; HISTORY_LEN],
+            count: 0,
+            head: 0
+        }
+    }
+
+    /// Records `line` as the most recently entered line, evicting the
+    /// oldest entry if the history is full. Lines longer than `LINE_CAP` are
+    /// rejected rather than silently truncated.
+    fn push(&mut self, line: &[u8]) {
+        if line.is_empty() || line.len() > LINE_CAP {
+            return;
+        }
+
+        let index = (self.head + self.count) % HISTORY_LEN;
+        self.lines[index][..line.len()].copy_from_slice(line);
+        self.lens[index] = line.len();
+
+        if self.count < HISTORY_LEN {
+            self.count += 1;
+        } else {
+            self.head = (self.head + 1) % HISTORY_LEN;
+        }
+    }
+
+    /// Returns the `n`th most recent line (`0` is the most recent), or
+    /// `None` if there aren't that many entries.
+    fn get(&self, n: usize) -> Option<&[u8]> {
+        if n >= self.count {
+            return None;
+        }
+
+        let index = (self.head + self.count - 1 - n) % HISTORY_LEN;
+        Some(&self.lines[index][..self.lens[index]])
+    }
+}
+
+/// Clears from the cursor's current on-screen column (`start`) to the end of
+/// `line`, reprints that tail, then moves the cursor back so it ends up at
+/// `cursor`.
+fn redraw_from(line: &[u8], start: usize, cursor: usize) {
+    kprint!("\x1b[K");
+    for &b in &line[start..] {
+        kprint!("{}", b as char);
+    }
+    let back = line.len() - cursor;
+    if back > 0 {
+        kprint!("\x1b[{}D", back);
+    }
+}
+
+/// Replaces the line currently on screen with `content`, used to recall a
+/// history entry. Updates `buf`/`len`/`cursor` to match.
+fn set_line(buf: &mut [u8], len: &mut usize, cursor: &mut usize, content: &[u8]) {
+    if *cursor > 0 {
+        kprint!("\x1b[{}D", *cursor);
+    }
+    kprint!("\x1b[K");
+
+    let n = content.len().min(buf.len());
+    buf[..n].copy_from_slice(&content[..n]);
+    *len = n;
+    *cursor = n;
+
+    for &b in &buf[..n] {
+        kprint!("{}", b as char);
+    }
 }
 
 /// Starts a shell using `prefix` as the prefix for each line. This function
 /// never returns: it is perpetually in a shell loop.
 pub fn shell(prefix: &str) {
+    let mut registry = Shell::new();
+    registry.register("echo", "print the given arguments", echo);
+
+    let mut history = History::new();
+
     loop {
         kprint!("{}", prefix);
-        let mut storage = [0u8; 512];
-        let mut input = StackVec::new(&mut storage);
+        let mut buf = [0u8; LINE_CAP];
+        let mut len: usize = 0;
+        let mut cursor: usize = 0;
+        let mut history_pos: Option<usize> = None;
+
         loop {
             let byte = CONSOLE.lock().read_byte();
-            kprint!("{}", byte as char); //vs &byte?
 
             if byte == 0x00 {
                 // ignore these bytes, I don't know where they come from
-            } 
+            }
+            else if byte == 0x1b {
+                // a VT100/ANSI escape sequence; only `ESC [ <letter>` is
+                // recognized, everything else is dropped on the floor
+                if CONSOLE.lock().read_byte() != b'[' {
+                    continue;
+                }
+
+                match CONSOLE.lock().read_byte() {
+                    b'A' => { // up: recall an older line
+                        let next = history_pos.map_or(0, |p| p + 1);
+                        if let Some(line) = history.get(next) {
+                            history_pos = Some(next);
+                            set_line(&mut buf, &mut len, &mut cursor, line);
+                        }
+                    }
+                    b'B' => { // down: recall a newer line, or clear
+                        match history_pos {
+                            Some(0) => {
+                                history_pos = None;
+                                set_line(&mut buf, &mut len, &mut cursor, &[]);
+                            }
+                            Some(p) => {
+                                if let Some(line) = history.get(p - 1) {
+                                    history_pos = Some(p - 1);
+                                    set_line(&mut buf, &mut len, &mut cursor, line);
+                                }
+                            }
+                            None => { () }
+                        }
+                    }
+                    b'C' => { // right: move the cursor forward
+                        if cursor < len {
+                            cursor += 1;
+                            kprint!("\x1b[C");
+                        }
+                    }
+                    b'D' => { // left: move the cursor backward
+                        if cursor > 0 {
+                            cursor -= 1;
+                            kprint!("\x1b[D");
+                        }
+                    }
+                    _ => { () }
+                }
+            }
             else if byte == 0x08 || byte == 0x7f {
-                if input.len() != 0 {
-                    kprint!("{}", 0x08 as char);
-                    kprint!(" ");
-                    kprint!("{}", 0x08 as char);
-                    input.pop();
-                } 
+                if cursor > 0 {
+                    buf.copy_within(cursor..len, cursor - 1);
+                    cursor -= 1;
+                    len -= 1;
+                    kprint!("\x1b[D");
+                    redraw_from(&buf[..len], cursor, cursor);
+                }
             }
             // if this byte is the end of the input
             else if byte == b'\n' || byte == b'\r' {
                 kprintln!("");
+                let line = &buf[..len];
                 let mut arguments: [&str; 64] = [""; 64]; // need to be inside this scope
-                match Command::parse(str::from_utf8(&input).unwrap(), &mut arguments) {
-                    Ok(command) => { 
-                        match command.path() {
-                            "echo" => { 
-                                for i in 1..command.args.len() {
-                                    kprint!("{} ", command.args[i]);
-                                }
-                                kprintln!("");
-                            }
-                            _ => { kprintln!("Error: unknown command: {}", command.path()); }
-                        }
-                    },
+                match Command::parse(str::from_utf8(line).unwrap(), &mut arguments) {
+                    Ok(command) => registry.dispatch(&command),
                     Err(Error::Empty) => { () },
                     Err(Error::TooManyArgs) => { kprintln!("\nError: too many arguments", ) }
                 }
+                history.push(line);
                 break;
             } else {
-                let result = input.push(byte);
-                match result {
-                    Ok(_) => { () },
-                    Err(_) => { 
-                        kprintln!("\nError: input is over 512 bytes long"); 
-                        break;
+                if len < buf.len() {
+                    if cursor < len {
+                        buf.copy_within(cursor..len, cursor + 1);
                     }
+                    buf[cursor] = byte;
+                    len += 1;
+                    cursor += 1;
+                    redraw_from(&buf[..len], cursor - 1, cursor);
+                } else {
+                    kprintln!("\nError: input is over {} bytes long", LINE_CAP);
+                    break;
                 }
             }
         }
@@ -1,6 +1,10 @@
+use core::arch::asm;
+use core::convert::Infallible;
 use core::marker::PhantomData;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use common::{IO_BASE, states};
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin, StatefulOutputPin};
 use volatile::prelude::*;
 use volatile::{Volatile, WriteVolatile, ReadVolatile, Reserved};
 
@@ -13,7 +17,7 @@ use volatile::{Volatile, WriteVolatile, ReadVolatile, Reserved};
 // 110 = GPIO Pin 9 takes alternate function 2
 // 111 = GPIO Pin 9 takes alternate function 3
 // 011 = GPIO Pin 9 takes alternate function 4
-// 010 = GPIO Pin 9 takes alternate function 5 
+// 010 = GPIO Pin 9 takes alternate function 5
 #[repr(u8)]
 pub enum Function {
     Input = 0b000,
@@ -65,15 +69,23 @@ states! {
     Uninitialized, Input, Output, Alt
 }
 
-/// A GPIP pin in state `State`.
+/// A GPIO pin number `PIN` in state `State`.
+///
+/// `PIN` is a compile-time constant in `0..=53`, and because each pin number
+/// is baked into the type, two `Gpio` handles for the same physical pin can
+/// never coexist, provided they were obtained through `Pins::take` (which
+/// can only succeed once — see its docs). Use `Pins::take` to obtain one
+/// `Gpio` per pin rather than constructing these directly; `Pins` only ever
+/// instantiates the 54 legal pin numbers, so the range check in `PinRange`
+/// exists to catch future internal misuse (e.g. a new constructor bypassing
+/// `Pins`) rather than anything reachable through the public API today.
 ///
 /// The `State` generic always corresponds to an uninstantiatable type that is
 /// use solely to mark and track the state of a given GPIO pin. A `Gpio`
 /// structure starts in the `Uninitialized` state and must be transitions into
 /// one of `Input`, `Output`, or `Alt` via the `into_input`, `into_output`, and
 /// `into_alt` methods before it can be used.
-pub struct Gpio<State> {
-    pin: u8,
+pub struct Gpio<const PIN: u8, State> {
     registers: &'static mut Registers,
     _state: PhantomData<State>
 }
@@ -81,43 +93,81 @@ pub struct Gpio<State> {
 /// The base address of the `GPIO` registers.
 const GPIO_BASE: usize = IO_BASE + 0x200000;
 
-impl<T> Gpio<T> {
+/// A compile-time range check for `PIN`, evaluated wherever `Gpio::new` is
+/// monomorphized. Since every call site today comes from the `pins!` macro
+/// with a literal 0..=53 value, this never actually fires; it's a guard
+/// against a future constructor being added that takes an arbitrary `PIN`.
+struct PinRange<const PIN: u8>;
+impl<const PIN: u8> PinRange<PIN> {
+    const ASSERT_VALID: () = assert!(PIN <= 53, "pin number exceeds maximum of 53");
+}
+
+/// The internal pull-up/pull-down resistor state for a pin.
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pull {
+    Off = 0b00,
+    Down = 0b01,
+    Up = 0b10
+}
+
+impl<const PIN: u8, T> Gpio<PIN, T> {
     /// Transitions `self` to state `S`, consuming `self` and returning a new
     /// `Gpio` instance in state `S`. This method should _never_ be exposed to
     /// the public!
     #[inline(always)]
-    fn transition<S>(self) -> Gpio<S> {
+    fn transition<S>(self) -> Gpio<PIN, S> {
         Gpio {
-            pin: self.pin,
             registers: self.registers,
             _state: PhantomData
         }
     }
+
+    /// Configures the internal pull-up/pull-down resistor for this pin,
+    /// following the BCM2837 clocked-write sequence: write the control value
+    /// to `PUD`, wait 150 cycles, clock it into the pad via `PUDCLK`, wait
+    /// another 150 cycles, then clear both registers.
+    fn set_pull_impl(&mut self, pull: Pull) {
+        let bank = (PIN / 32) as usize;
+        let bit = (PIN % 32) as u32;
+
+        self.registers.PUD.write(pull as u32);
+        spin_sleep_cycles(150);
+        self.registers.PUDCLK[bank].write(1 << bit);
+        spin_sleep_cycles(150);
+        self.registers.PUD.write(0);
+        self.registers.PUDCLK[bank].write(0);
+    }
 }
 
-impl Gpio<Uninitialized> {
-    /// Returns a new `GPIO` structure for pin number `pin`.
-    ///
-    /// # Panics
+/// Busy-waits for at least `cycles` clock cycles.
+#[inline(always)]
+fn spin_sleep_cycles(cycles: u32) {
+    for _ in 0..cycles {
+        unsafe { asm!("nop", options(nomem, nostack, preserves_flags)) };
+    }
+}
+
+impl<const PIN: u8> Gpio<PIN, Uninitialized> {
+    /// Returns a new `Gpio` structure for pin number `PIN`.
     ///
-    /// Panics if `pin` > `53`.
-    pub fn new(pin: u8) -> Gpio<Uninitialized> {
-        if pin > 53 {
-            panic!("Gpio::new(): pin {} exceeds maximum of 53", pin);
-        }
+    /// Only called by `Pins::take`, which hands out each pin exactly once;
+    /// not exposed publicly so that two handles for the same pin can't be
+    /// created.
+    fn new() -> Gpio<PIN, Uninitialized> {
+        let () = PinRange::<PIN>::ASSERT_VALID;
 
         Gpio {
             registers: unsafe { &mut *(GPIO_BASE as *mut Registers) },
-            pin: pin,
             _state: PhantomData
         }
     }
 
     /// Enables the alternative function `function` for `self`. Consumes self
     /// and returns a `Gpio` structure in the `Alt` state.
-    pub fn into_alt(self, function: Function) -> Gpio<Alt> {
-        let index: usize = (self.pin / 10) as usize; // find which register
-        let shift: usize = (self.pin as usize - index * 10) * 3; // find the bits
+    pub fn into_alt(self, function: Function) -> Gpio<PIN, Alt> {
+        let index: usize = (PIN / 10) as usize; // find which register
+        let shift: usize = (PIN as usize - index * 10) * 3; // find the bits
 
         {
             let register: &mut Volatile<u32> = &mut self.registers.FSEL[index]; // get the register
@@ -130,33 +180,256 @@ impl Gpio<Uninitialized> {
 
     /// Sets this pin to be an _output_ pin. Consumes self and returns a `Gpio`
     /// structure in the `Output` state.
-    pub fn into_output(self) -> Gpio<Output> {
+    pub fn into_output(self) -> Gpio<PIN, Output> {
         self.into_alt(Function::Output).transition()
     }
 
     /// Sets this pin to be an _input_ pin. Consumes self and returns a `Gpio`
     /// structure in the `Input` state.
-    pub fn into_input(self) -> Gpio<Input> {
+    pub fn into_input(self) -> Gpio<PIN, Input> {
         self.into_alt(Function::Input).transition()
     }
 }
 
-impl Gpio<Output> {
+impl<const PIN: u8> Gpio<PIN, Alt> {
+    /// Configures the internal pull-up/pull-down resistor for this pin. See
+    /// `Gpio::set_pull_impl` for the register sequence.
+    pub fn set_pull(&mut self, pull: Pull) {
+        self.set_pull_impl(pull);
+    }
+}
+
+impl<const PIN: u8> Gpio<PIN, Output> {
     /// Sets (turns on) the pin.
     pub fn set(&mut self) {
-        unimplemented!()
+        let bank = (PIN / 32) as usize;
+        let bit = (PIN % 32) as u32;
+        self.registers.SET[bank].write(1 << bit);
     }
 
     /// Clears (turns off) the pin.
     pub fn clear(&mut self) {
-        unimplemented!()
+        let bank = (PIN / 32) as usize;
+        let bit = (PIN % 32) as u32;
+        self.registers.CLR[bank].write(1 << bit);
+    }
+
+    /// Configures the internal pull-up/pull-down resistor for this pin. See
+    /// `Gpio::set_pull_impl` for the register sequence.
+    pub fn set_pull(&mut self, pull: Pull) {
+        self.set_pull_impl(pull);
     }
 }
 
-impl Gpio<Input> {
+/// A GPIO event that can be detected on an input pin.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Synchronous rising edge (`REN`).
+    RisingEdge,
+    /// Synchronous falling edge (`FEN`).
+    FallingEdge,
+    /// High level (`HEN`).
+    HighLevel,
+    /// Low level (`LEN`).
+    LowLevel,
+    /// Asynchronous rising edge (`AREN`), not synchronized to the clock.
+    AsyncRisingEdge,
+    /// Asynchronous falling edge (`AFEN`), not synchronized to the clock.
+    AsyncFallingEdge
+}
+
+impl<const PIN: u8> Gpio<PIN, Input> {
     /// Reads the pin's value. Returns `true` if the level is high and `false`
     /// if the level is low.
     pub fn level(&mut self) -> bool {
-        unimplemented!()
+        let bank = (PIN / 32) as usize;
+        let bit = (PIN % 32) as u32;
+        self.registers.LEV[bank].read() & (1 << bit) != 0
+    }
+
+    /// Returns the register bank that detects `event` for this pin.
+    fn event_bank(&mut self, event: Event) -> &mut Volatile<u32> {
+        let bank = (PIN / 32) as usize;
+        match event {
+            Event::RisingEdge => &mut self.registers.REN[bank],
+            Event::FallingEdge => &mut self.registers.FEN[bank],
+            Event::HighLevel => &mut self.registers.HEN[bank],
+            Event::LowLevel => &mut self.registers.LEN[bank],
+            Event::AsyncRisingEdge => &mut self.registers.AREN[bank],
+            Event::AsyncFallingEdge => &mut self.registers.AFEN[bank]
+        }
+    }
+
+    /// Enables detection of `event` on this pin.
+    pub fn enable_detect(&mut self, event: Event) {
+        let bit = 1 << (PIN % 32) as u32;
+        let register = self.event_bank(event);
+        let read = register.read();
+        register.write(read | bit);
+    }
+
+    /// Disables detection of `event` on this pin.
+    pub fn disable_detect(&mut self, event: Event) {
+        let bit = 1 << (PIN % 32) as u32;
+        let register = self.event_bank(event);
+        let read = register.read();
+        register.write(read & !bit);
     }
+
+    /// Returns `true` if an enabled event has been detected on this pin since
+    /// it was last cleared with `clear_event`.
+    pub fn is_pending(&self) -> bool {
+        let bank = (PIN / 32) as usize;
+        let bit = 1 << (PIN % 32) as u32;
+        self.registers.EDS[bank].read() & bit != 0
+    }
+
+    /// Clears the pending event status for this pin.
+    pub fn clear_event(&mut self) {
+        let bank = (PIN / 32) as usize;
+        let bit = 1 << (PIN % 32) as u32;
+        self.registers.EDS[bank].write(bit);
+    }
+
+    /// Configures the internal pull-up/pull-down resistor for this pin. See
+    /// `Gpio::set_pull_impl` for the register sequence.
+    pub fn set_pull(&mut self, pull: Pull) {
+        self.set_pull_impl(pull);
+    }
+}
+
+impl<const PIN: u8> ErrorType for Gpio<PIN, Output> {
+    type Error = Infallible;
 }
+
+impl<const PIN: u8> OutputPin for Gpio<PIN, Output> {
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.set();
+        Ok(())
+    }
+
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.clear();
+        Ok(())
+    }
+}
+
+impl<const PIN: u8> StatefulOutputPin for Gpio<PIN, Output> {
+    fn is_set_high(&mut self) -> Result<bool, Infallible> {
+        let bank = (PIN / 32) as usize;
+        let bit = (PIN % 32) as u32;
+        Ok(self.registers.LEV[bank].read() & (1 << bit) != 0)
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.is_set_high()?)
+    }
+}
+
+impl<const PIN: u8> Gpio<PIN, Output> {
+    /// Flips the pin: sets it low if it currently reads high, high
+    /// otherwise. The current level is read from the `LEV` register; the
+    /// level last written via `set`/`clear` is not stored separately.
+    pub fn toggle(&mut self) -> Result<(), Infallible> {
+        if self.is_set_high()? {
+            self.set_low()
+        } else {
+            self.set_high()
+        }
+    }
+}
+
+impl<const PIN: u8> ErrorType for Gpio<PIN, Input> {
+    type Error = Infallible;
+}
+
+impl<const PIN: u8> InputPin for Gpio<PIN, Input> {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        let bank = (PIN / 32) as usize;
+        let bit = (PIN % 32) as u32;
+        Ok(self.registers.LEV[bank].read() & (1 << bit) != 0)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.is_high()?)
+    }
+}
+
+/// Tracks whether the singleton `Pins` has already been handed out, the same
+/// singleton-peripherals pattern other embedded HALs (e.g. `cortex-m`'s
+/// `Peripherals::take`) use to make "exactly once" an enforced runtime
+/// invariant rather than a comment.
+static PINS_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Generates the `Pins` splitter struct, handing out one named `Gpio` field
+/// per physical pin so that all 54 pins are available and none can be
+/// obtained twice.
+macro_rules! pins {
+    ($($number:expr => $name:ident),* $(,)?) => {
+        /// The full set of BCM2837 GPIO pins, each available exactly once.
+        ///
+        /// Obtain this via `Pins::take`; splitting it into its fields (e.g.
+        /// `let Pins { gpio14, gpio15, .. } = pins;`) hands out individual,
+        /// uniquely-typed `Gpio` handles.
+        #[allow(non_snake_case)]
+        pub struct Pins {
+            $(pub $name: Gpio<$number, Uninitialized>),*
+        }
+
+        impl Pins {
+            /// Returns the singleton set of GPIO pins, or `None` if it has
+            /// already been taken. This is what makes it impossible for two
+            /// `Gpio` handles to alias the same pin's registers.
+            pub fn take() -> Option<Pins> {
+                if PINS_TAKEN.swap(true, Ordering::AcqRel) {
+                    return None;
+                }
+
+                Some(Pins {
+                    $($name: Gpio::new()),*
+                })
+            }
+        }
+    };
+}
+
+pins! {
+    0 => gpio0, 1 => gpio1, 2 => gpio2, 3 => gpio3, 4 => gpio4, 5 => gpio5,
+    6 => gpio6, 7 => gpio7, 8 => gpio8, 9 => gpio9, 10 => gpio10,
+    11 => gpio11, 12 => gpio12, 13 => gpio13, 14 => gpio14, 15 => gpio15,
+    16 => gpio16, 17 => gpio17, 18 => gpio18, 19 => gpio19, 20 => gpio20,
+    21 => gpio21, 22 => gpio22, 23 => gpio23, 24 => gpio24, 25 => gpio25,
+    26 => gpio26, 27 => gpio27, 28 => gpio28, 29 => gpio29, 30 => gpio30,
+    31 => gpio31, 32 => gpio32, 33 => gpio33, 34 => gpio34, 35 => gpio35,
+    36 => gpio36, 37 => gpio37, 38 => gpio38, 39 => gpio39, 40 => gpio40,
+    41 => gpio41, 42 => gpio42, 43 => gpio43, 44 => gpio44, 45 => gpio45,
+    46 => gpio46, 47 => gpio47, 48 => gpio48, 49 => gpio49, 50 => gpio50,
+    51 => gpio51, 52 => gpio52, 53 => gpio53,
+}
+
+// The aliases below are named-type convenience only: they pin down which
+// physical pin is meant for a given peripheral, but `Alt` doesn't carry
+// which alternate function was actually selected, so `pins.gpio14.into_alt(
+// Function::Alt5)` still type-checks as a `Uart0Tx` even though UART0 needs
+// `Alt0`. Callers must still pass the right `Function` to `into_alt`.
+
+/// UART0 (PL011) transmit pin; configure with `into_alt(Function::Alt0)`.
+pub type Uart0Tx = Gpio<14, Alt>;
+/// UART0 (PL011) receive pin; configure with `into_alt(Function::Alt0)`.
+pub type Uart0Rx = Gpio<15, Alt>;
+
+/// SPI0 chip-select 1; configure with `into_alt(Function::Alt0)`.
+pub type Spi0Ce1 = Gpio<7, Alt>;
+/// SPI0 chip-select 0; configure with `into_alt(Function::Alt0)`.
+pub type Spi0Ce0 = Gpio<8, Alt>;
+/// SPI0 MISO; configure with `into_alt(Function::Alt0)`.
+pub type Spi0Miso = Gpio<9, Alt>;
+/// SPI0 MOSI; configure with `into_alt(Function::Alt0)`.
+pub type Spi0Mosi = Gpio<10, Alt>;
+/// SPI0 serial clock; configure with `into_alt(Function::Alt0)`.
+pub type Spi0Sclk = Gpio<11, Alt>;
+
+/// I2C1 data line; configure with `into_alt(Function::Alt0)`.
+pub type I2c1Sda = Gpio<2, Alt>;
+/// I2C1 clock line; configure with `into_alt(Function::Alt0)`.
+pub type I2c1Scl = Gpio<3, Alt>;